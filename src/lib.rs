@@ -1,14 +1,17 @@
-use std::f32::consts::FRAC_PI_2;
+use std::{collections::HashSet, f32::consts::FRAC_PI_2};
 
 use bevy::{
     color::palettes::tailwind::*,
-    input::mouse::AccumulatedMouseMotion,
+    core_pipeline::Skybox,
+    input::mouse::{AccumulatedMouseMotion, MouseScrollUnit, MouseWheel},
     picking::pointer::PointerInteraction,
     prelude::*,
+    render::render_resource::{TextureViewDescriptor, TextureViewDimension},
     scene::{SceneInstance, SceneInstanceReady},
-    window::{CursorGrabMode, PrimaryWindow},
+    window::{CursorGrabMode, PrimaryWindow, SystemCursorIcon},
+    winit::cursor::CursorIcon,
 };
-use bevy_inspector_egui::{bevy_egui::EguiContext, quick::WorldInspectorPlugin};
+use bevy_inspector_egui::quick::WorldInspectorPlugin;
 
 pub struct GamePlugin;
 
@@ -24,24 +27,439 @@ impl Plugin for GamePlugin {
         }));
 
         app.register_type::<Board>();
+        app.register_type::<OrbitCamera>();
+        app.register_type::<CameraSettings>();
+        app.init_resource::<CameraSettings>();
+        app.init_resource::<CameraCycle>();
+        app.register_type::<EnvironmentSettings>();
+        app.init_resource::<EnvironmentSettings>();
+        app.init_resource::<CursorIcons>();
+        app.init_resource::<CursorState>();
+        app.register_type::<KeyBindings>();
+        app.init_resource::<KeyBindings>();
+        app.register_type::<MovementSettings>();
+        app.init_resource::<MovementSettings>();
 
         app.add_plugins((MeshPickingPlugin, WorldInspectorPlugin::default()));
 
-        app.add_systems(Startup, (setup_camera, setup_lights, setup_board));
-        app.add_systems(Update, (/*move_camera,*/draw_mesh_intersections));
+        app.add_systems(
+            Startup,
+            (
+                (setup_camera, setup_flycam).chain(),
+                setup_lights,
+                setup_board,
+            ),
+        );
+        app.add_systems(
+            Update,
+            (
+                orbit_camera,
+                fly_camera,
+                cycle_camera,
+                apply_environment_map,
+                draw_mesh_intersections,
+            ),
+        );
     }
 }
 
-fn setup_camera(mut commands: Commands) {
-    commands.spawn((
-        Camera3d::default(),
-        Camera {
-            hdr: true,
-            ..default()
-        },
-        Transform::from_translation(Vec3::new(0.0, 1.5, 1.5))
-            .looking_at(Vec3::new(0.0, 0.0, 0.0), Vec3::Y),
-    ));
+/// The cubemap used for the board's environment (skybox + image-based
+/// lighting), and the brightness it's applied at. Both are read once, when
+/// `setup_camera` spawns the orbit camera; changing them afterwards does not
+/// reload the cubemap or update the camera's skybox and lighting.
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+struct EnvironmentSettings {
+    cubemap_path: String,
+    brightness: f32,
+}
+
+impl Default for EnvironmentSettings {
+    fn default() -> Self {
+        Self {
+            cubemap_path: "environment_maps/pisa_cubemap.png".to_string(),
+            brightness: 1000.0,
+        }
+    }
+}
+
+/// Tracks the in-flight cubemap image handle so `apply_environment_map` can
+/// reinterpret it as a cube texture exactly once, after it finishes loading.
+#[derive(Resource)]
+struct Cubemap {
+    handle: Handle<Image>,
+    is_loaded: bool,
+}
+
+/// Cursor icons resolved once and cloned onto the window's `CursorIcon` on
+/// hover/drag, rather than constructing a new value on every pointer event.
+#[derive(Resource)]
+struct CursorIcons {
+    hover: CursorIcon,
+    grab: CursorIcon,
+}
+
+impl Default for CursorIcons {
+    fn default() -> Self {
+        Self {
+            hover: CursorIcon::System(SystemCursorIcon::Pointer),
+            grab: CursorIcon::System(SystemCursorIcon::Grabbing),
+        }
+    }
+}
+
+/// Tracks which pickable meshes are currently hovered and whether a drag is
+/// in progress, so the cursor observers only change the icon when the
+/// interaction actually warrants it.
+#[derive(Resource, Default)]
+struct CursorState {
+    hovered: HashSet<Entity>,
+    dragging: bool,
+}
+
+/// The ordered set of cameras the player can cycle through with `cycle_camera`:
+/// the default orbit camera, the free-fly debug camera, and any `Camera3d`
+/// nodes authored in the board's glTF scene, in that order.
+#[derive(Resource, Default)]
+struct CameraCycle {
+    entities: Vec<Entity>,
+    active: usize,
+}
+
+/// Orbits a camera around a focus point at a given radius, driven by
+/// right-drag (yaw/pitch) and the scroll wheel (zoom).
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+struct OrbitCamera {
+    focus: Vec3,
+    radius: f32,
+    yaw: f32,
+    pitch: f32,
+}
+
+impl Default for OrbitCamera {
+    fn default() -> Self {
+        Self {
+            focus: Vec3::ZERO,
+            radius: 3.0,
+            yaw: 0.0,
+            pitch: 0.4,
+        }
+    }
+}
+
+/// Tunables for [`orbit_camera`], exposed to the inspector so feel can be
+/// adjusted without recompiling.
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+struct CameraSettings {
+    sensitivity: f32,
+    zoom_speed: f32,
+    min_radius: f32,
+    max_radius: f32,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            sensitivity: 0.004,
+            zoom_speed: 0.5,
+            min_radius: 1.5,
+            max_radius: 10.0,
+        }
+    }
+}
+
+fn setup_camera(
+    mut commands: Commands,
+    mut camera_cycle: ResMut<CameraCycle>,
+    asset_server: Res<AssetServer>,
+    environment: Res<EnvironmentSettings>,
+) {
+    let orbit = OrbitCamera::default();
+    let translation = orbit.focus
+        + orbit.radius
+            * Vec3::new(
+                orbit.yaw.cos() * orbit.pitch.cos(),
+                orbit.pitch.sin(),
+                orbit.yaw.sin() * orbit.pitch.cos(),
+            );
+
+    let cubemap_handle: Handle<Image> = asset_server.load(&environment.cubemap_path);
+
+    let entity = commands
+        .spawn((
+            Camera3d::default(),
+            Camera {
+                hdr: true,
+                ..default()
+            },
+            Transform::from_translation(translation).looking_at(orbit.focus, Vec3::Y),
+            orbit,
+            Skybox {
+                image: cubemap_handle.clone(),
+                brightness: environment.brightness,
+                ..default()
+            },
+            EnvironmentMapLight {
+                diffuse_map: cubemap_handle.clone(),
+                specular_map: cubemap_handle.clone(),
+                intensity: environment.brightness,
+                ..default()
+            },
+        ))
+        .id();
+
+    commands.insert_resource(Cubemap {
+        handle: cubemap_handle,
+        is_loaded: false,
+    });
+
+    camera_cycle.entities.push(entity);
+    camera_cycle.active = 0;
+}
+
+/// Once the cubemap image finishes loading, reinterprets it as a cube
+/// texture (PNGs carry no metadata marking them as cubemaps, so Bevy
+/// treats them as a single flat image until told otherwise).
+fn apply_environment_map(
+    asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+    mut cubemap: ResMut<Cubemap>,
+) {
+    if cubemap.is_loaded || !asset_server.is_loaded_with_dependencies(&cubemap.handle) {
+        return;
+    }
+
+    if let Some(image) = images.get_mut(&cubemap.handle) {
+        if image.texture_descriptor.array_layer_count() == 1 {
+            image.reinterpret_stacked_2d_as_array(image.height() / image.width());
+            image.texture_view_descriptor = Some(TextureViewDescriptor {
+                dimension: Some(TextureViewDimension::Cube),
+                ..default()
+            });
+        }
+        cubemap.is_loaded = true;
+    }
+}
+
+/// Presses of `C` switch the active camera to the next entry in
+/// [`CameraCycle`], disabling every other camera in the list.
+fn cycle_camera(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut camera_cycle: ResMut<CameraCycle>,
+    mut cameras: Query<&mut Camera>,
+) {
+    if camera_cycle.entities.len() < 2 || !keyboard_input.just_pressed(KeyCode::KeyC) {
+        return;
+    }
+
+    camera_cycle.active = (camera_cycle.active + 1) % camera_cycle.entities.len();
+
+    for (index, &entity) in camera_cycle.entities.iter().enumerate() {
+        if let Ok(mut camera) = cameras.get_mut(entity) {
+            camera.is_active = index == camera_cycle.active;
+        }
+    }
+}
+
+/// Remappable keys for the free-fly debug camera.
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+struct KeyBindings {
+    move_forward: KeyCode,
+    move_backward: KeyCode,
+    move_left: KeyCode,
+    move_right: KeyCode,
+    move_up: KeyCode,
+    move_down: KeyCode,
+    run: KeyCode,
+    toggle_cursor_grab: KeyCode,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            move_forward: KeyCode::KeyW,
+            move_backward: KeyCode::KeyS,
+            move_left: KeyCode::KeyA,
+            move_right: KeyCode::KeyD,
+            move_up: KeyCode::KeyE,
+            move_down: KeyCode::KeyQ,
+            run: KeyCode::ShiftLeft,
+            toggle_cursor_grab: KeyCode::KeyG,
+        }
+    }
+}
+
+/// Feel tunables for the free-fly debug camera.
+#[derive(Resource, Reflect)]
+#[reflect(Resource)]
+struct MovementSettings {
+    sensitivity: f32,
+    speed: f32,
+    run_multiplier: f32,
+    friction: f32,
+}
+
+impl Default for MovementSettings {
+    fn default() -> Self {
+        Self {
+            sensitivity: 0.003,
+            speed: 5.0,
+            run_multiplier: 2.5,
+            friction: 10.0,
+        }
+    }
+}
+
+/// Marks the free-fly debug camera driven by [`fly_camera`], one of the
+/// entries in [`CameraCycle`].
+#[derive(Component, Default)]
+struct FlyCam {
+    velocity: Vec3,
+}
+
+fn setup_flycam(mut commands: Commands, mut camera_cycle: ResMut<CameraCycle>) {
+    let entity = commands
+        .spawn((
+            Camera3d::default(),
+            Camera {
+                hdr: true,
+                is_active: false,
+                ..default()
+            },
+            Transform::from_translation(Vec3::new(0.0, 1.5, 1.5)).looking_at(Vec3::ZERO, Vec3::Y),
+            FlyCam::default(),
+            Name::new("FlyCam"),
+        ))
+        .id();
+
+    camera_cycle.entities.push(entity);
+}
+
+/// Drives the active [`FlyCam`] with configurable WASD/QE movement and
+/// mouse-look, toggling cursor grab via [`KeyBindings::toggle_cursor_grab`]
+/// rather than only while a mouse button is held.
+fn fly_camera(
+    key_bindings: Res<KeyBindings>,
+    movement_settings: Res<MovementSettings>,
+    mouse_input: Res<AccumulatedMouseMotion>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    time: Res<Time>,
+    mut window: Query<&mut Window, With<PrimaryWindow>>,
+    mut flycams: Query<(&mut Transform, &mut FlyCam, &Camera)>,
+) {
+    let Ok(mut window) = window.get_single_mut() else {
+        return;
+    };
+
+    if keyboard_input.just_pressed(key_bindings.toggle_cursor_grab) {
+        let grabbed = window.cursor_options.grab_mode == CursorGrabMode::Locked;
+        window.cursor_options.grab_mode = if grabbed {
+            CursorGrabMode::None
+        } else {
+            CursorGrabMode::Locked
+        };
+        window.cursor_options.visible = grabbed;
+    }
+
+    let grabbed = window.cursor_options.grab_mode == CursorGrabMode::Locked;
+
+    for (mut transform, mut flycam, camera) in &mut flycams {
+        if !camera.is_active || !grabbed {
+            continue;
+        }
+
+        let mut direction = Vec3::ZERO;
+        if keyboard_input.pressed(key_bindings.move_forward) {
+            direction += *transform.forward();
+        }
+        if keyboard_input.pressed(key_bindings.move_backward) {
+            direction -= *transform.forward();
+        }
+        if keyboard_input.pressed(key_bindings.move_left) {
+            direction -= *transform.right();
+        }
+        if keyboard_input.pressed(key_bindings.move_right) {
+            direction += *transform.right();
+        }
+        if keyboard_input.pressed(key_bindings.move_up) {
+            direction += Vec3::Y;
+        }
+        if keyboard_input.pressed(key_bindings.move_down) {
+            direction -= Vec3::Y;
+        }
+
+        let speed = if keyboard_input.pressed(key_bindings.run) {
+            movement_settings.speed * movement_settings.run_multiplier
+        } else {
+            movement_settings.speed
+        };
+
+        let target_velocity = if direction != Vec3::ZERO {
+            direction.normalize() * speed
+        } else {
+            Vec3::ZERO
+        };
+
+        // Ease towards the target velocity rather than adding a fixed step per
+        // frame, so movement feel is independent of frame rate.
+        let ease = 1.0 - (-movement_settings.friction * time.delta_secs()).exp();
+        flycam.velocity = flycam.velocity.lerp(target_velocity, ease);
+        transform.translation += flycam.velocity * time.delta_secs();
+
+        let delta = mouse_input.delta;
+        if delta != Vec2::ZERO {
+            let delta_yaw = -delta.x * movement_settings.sensitivity;
+            let delta_pitch = -delta.y * movement_settings.sensitivity;
+
+            let (yaw, pitch, roll) = transform.rotation.to_euler(EulerRot::YXZ);
+            let yaw = yaw + delta_yaw;
+
+            const PITCH_LIMIT: f32 = FRAC_PI_2 - 0.01;
+            let pitch = (pitch + delta_pitch).clamp(-PITCH_LIMIT, PITCH_LIMIT);
+
+            transform.rotation = Quat::from_euler(EulerRot::YXZ, yaw, pitch, roll);
+        }
+    }
+}
+
+/// Right-drag to orbit, scroll to zoom, focused on the board.
+fn orbit_camera(
+    settings: Res<CameraSettings>,
+    mouse_input: Res<AccumulatedMouseMotion>,
+    mouse_button_input: Res<ButtonInput<MouseButton>>,
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    mut cameras: Query<(&mut Transform, &mut OrbitCamera)>,
+) {
+    const PITCH_LIMIT: f32 = FRAC_PI_2 - 0.05;
+
+    for (mut transform, mut orbit) in &mut cameras {
+        if mouse_button_input.pressed(MouseButton::Right) {
+            let delta = mouse_input.delta;
+            orbit.yaw += delta.x * settings.sensitivity;
+            orbit.pitch =
+                (orbit.pitch - delta.y * settings.sensitivity).clamp(-PITCH_LIMIT, PITCH_LIMIT);
+        }
+
+        for event in mouse_wheel_events.read() {
+            let scroll = match event.unit {
+                MouseScrollUnit::Line => event.y,
+                MouseScrollUnit::Pixel => event.y * 0.01,
+            };
+            orbit.radius = (orbit.radius - scroll * settings.zoom_speed)
+                .clamp(settings.min_radius, settings.max_radius);
+        }
+
+        let direction = Vec3::new(
+            orbit.yaw.cos() * orbit.pitch.cos(),
+            orbit.pitch.sin(),
+            orbit.yaw.sin() * orbit.pitch.cos(),
+        );
+        transform.translation = orbit.focus + orbit.radius * direction;
+        *transform = transform.looking_at(orbit.focus, Vec3::Y);
+    }
 }
 
 fn setup_lights(mut commands: Commands) {
@@ -73,6 +491,9 @@ fn on_board_added(
     mut commands: Commands,
     mut materials: ResMut<Assets<StandardMaterial>>,
     mesh3ds: Query<&Mesh3d>,
+    camera3ds: Query<&Camera3d>,
+    mut cameras: Query<&mut Camera>,
+    mut camera_cycle: ResMut<CameraCycle>,
 ) {
     // Set up the materials.
     let white_matl = materials.add(Color::WHITE);
@@ -89,7 +510,18 @@ fn on_board_added(
                 // .observe(update_material_on::<Pointer<Out>>(white_matl.clone()))
                 // .observe(update_material_on::<Pointer<Down>>(pressed_matl.clone()))
                 // .observe(update_material_on::<Pointer<Up>>(hover_matl.clone()))
-                .observe(rotate_on_drag);
+                .observe(rotate_on_drag)
+                .observe(on_pointer_over)
+                .observe(on_pointer_out)
+                .observe(on_pointer_drag_start)
+                .observe(on_pointer_drag_end);
+        }
+
+        if camera3ds.get(child).is_ok() {
+            if let Ok(mut camera) = cameras.get_mut(child) {
+                camera.is_active = false;
+            }
+            camera_cycle.entities.push(child);
         }
     }
 }
@@ -126,96 +558,78 @@ fn rotate_on_drag(drag: Trigger<Pointer<Drag>>, mut transforms: Query<&mut Trans
     transform.rotate_y(drag.delta.x * 0.02);
 }
 
-fn move_camera(
-    mouse_input: Res<AccumulatedMouseMotion>,
-    mouse_button_input: Res<ButtonInput<MouseButton>>,
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    time: Res<Time>,
-    mut camera: Query<&mut Transform, With<Camera3d>>,
-    mut window: Query<&mut Window, With<PrimaryWindow>>,
-    mut egui_context: Query<&mut EguiContext>,
+/// An observer that shows a pointing-hand cursor while hovering a pickable mesh.
+fn on_pointer_over(
+    over: Trigger<Pointer<Over>>,
+    cursor_icons: Res<CursorIcons>,
+    mut cursor_state: ResMut<CursorState>,
+    mut commands: Commands,
+    window: Query<Entity, With<PrimaryWindow>>,
 ) {
-    let mut egui_context = egui_context.get_single_mut().unwrap();
+    cursor_state.hovered.insert(over.entity());
 
-    if mouse_button_input.just_released(MouseButton::Middle) {
-        if let Ok(mut window) = window.get_single_mut() {
-            window.cursor_options.grab_mode = CursorGrabMode::None;
-            window.cursor_options.visible = true;
-        }
+    if cursor_state.dragging {
+        return;
     }
 
-    if mouse_button_input.just_pressed(MouseButton::Middle)
-        && !egui_context.get_mut().wants_pointer_input()
-    {
-        if let Ok(mut window) = window.get_single_mut() {
-            window.cursor_options.grab_mode = CursorGrabMode::Locked;
-            window.cursor_options.visible = false;
-        }
+    if let Ok(window) = window.get_single() {
+        commands.entity(window).insert(cursor_icons.hover.clone());
     }
+}
 
-    if let Ok(window) = window.get_single_mut() {
-        if window.cursor_options.grab_mode != CursorGrabMode::Locked {
-            return;
-        }
-    }
+/// An observer that reverts the cursor to the default icon when the pointer
+/// leaves a mesh, unless another mesh is still hovered or a drag is still in
+/// progress (the grab icon owns the cursor until the drag ends).
+fn on_pointer_out(
+    out: Trigger<Pointer<Out>>,
+    mut cursor_state: ResMut<CursorState>,
+    mut commands: Commands,
+    window: Query<Entity, With<PrimaryWindow>>,
+) {
+    cursor_state.hovered.remove(&out.entity());
 
-    for mut transform in &mut camera {
-        let mut direction = Vec3::ZERO;
+    if cursor_state.dragging || !cursor_state.hovered.is_empty() {
+        return;
+    }
 
-        if keyboard_input.pressed(KeyCode::KeyW) {
-            info!("W pressed");
-            direction += *transform.forward();
-        }
-        if keyboard_input.pressed(KeyCode::KeyS) {
-            info!("S pressed");
-            direction -= *transform.forward();
-        }
-        if keyboard_input.pressed(KeyCode::KeyA) {
-            info!("A pressed");
-            direction -= *transform.right();
-        }
-        if keyboard_input.pressed(KeyCode::KeyD) {
-            info!("D pressed");
-            direction += *transform.right();
-        }
-        if keyboard_input.pressed(KeyCode::KeyE) {
-            info!("E pressed");
-            direction += Vec3::Y;
-        }
-        if keyboard_input.pressed(KeyCode::KeyQ) {
-            info!("Q pressed");
-            direction -= Vec3::Y;
-        }
+    if let Ok(window) = window.get_single() {
+        commands.entity(window).remove::<CursorIcon>();
+    }
+}
 
-        if direction != Vec3::ZERO {
-            direction = direction.normalize();
-            transform.translation += direction * 5.0 * time.delta_secs();
-        }
+/// An observer that shows a grabbing-hand cursor while a mesh is being dragged.
+fn on_pointer_drag_start(
+    _drag_start: Trigger<Pointer<DragStart>>,
+    cursor_icons: Res<CursorIcons>,
+    mut cursor_state: ResMut<CursorState>,
+    mut commands: Commands,
+    window: Query<Entity, With<PrimaryWindow>>,
+) {
+    cursor_state.dragging = true;
 
-        let delta = mouse_input.delta;
-        if delta != Vec2::ZERO {
-            // Note that we are not multiplying by delta_time here.
-            // The reason is that for mouse movement, we already get the full movement that happened since the last frame.
-            // This means that if we multiply by delta_time, we will get a smaller rotation than intended by the user.
-            // This situation is reversed when reading e.g. analog input from a gamepad however, where the same rules
-            // as for keyboard input apply. Such an input should be multiplied by delta_time to get the intended rotation
-            // independent of the framerate.
-            let delta_yaw = -delta.x * 0.003;
-            let delta_pitch = -delta.y * 0.002;
+    if let Ok(window) = window.get_single() {
+        commands.entity(window).insert(cursor_icons.grab.clone());
+    }
+}
 
-            let (yaw, pitch, roll) = transform.rotation.to_euler(EulerRot::YXZ);
-            let yaw = yaw + delta_yaw;
+/// An observer that restores the hover cursor once a drag ends, or reverts
+/// to the default icon if the pointer ended up off every mesh.
+fn on_pointer_drag_end(
+    _drag_end: Trigger<Pointer<DragEnd>>,
+    cursor_icons: Res<CursorIcons>,
+    mut cursor_state: ResMut<CursorState>,
+    mut commands: Commands,
+    window: Query<Entity, With<PrimaryWindow>>,
+) {
+    cursor_state.dragging = false;
 
-            // If the pitch was ±¹⁄₂ π, the camera would look straight up or down.
-            // When the user wants to move the camera back to the horizon, which way should the camera face?
-            // The camera has no way of knowing what direction was "forward" before landing in that extreme position,
-            // so the direction picked will for all intents and purposes be arbitrary.
-            // Another issue is that for mathematical reasons, the yaw will effectively be flipped when the pitch is at the extremes.
-            // To not run into these issues, we clamp the pitch to a safe range.
-            const PITCH_LIMIT: f32 = FRAC_PI_2 - 0.01;
-            let pitch = (pitch + delta_pitch).clamp(-PITCH_LIMIT, PITCH_LIMIT);
+    let Ok(window) = window.get_single() else {
+        return;
+    };
 
-            transform.rotation = Quat::from_euler(EulerRot::YXZ, yaw, pitch, roll);
-        }
+    if cursor_state.hovered.is_empty() {
+        commands.entity(window).remove::<CursorIcon>();
+    } else {
+        commands.entity(window).insert(cursor_icons.hover.clone());
     }
 }